@@ -0,0 +1,31 @@
+//! Copies a pre-generated startup snapshot into `OUT_DIR` when the `snapshot` feature is
+//! enabled, so `runtime::snapshot::STARTUP_SNAPSHOT` has a file to `include_bytes!`.
+//!
+//! The snapshot itself has to be produced ahead of time by running
+//! `cargo run --bin snapshot -- RUNTIME_SNAPSHOT.bin` against a non-snapshot build - this
+//! script can't generate it on the fly because that would require the crate to already be
+//! built with the feature it's trying to enable.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=RUNTIME_SNAPSHOT.bin");
+
+    if env::var_os("CARGO_FEATURE_SNAPSHOT").is_none() {
+        return;
+    }
+
+    let src = PathBuf::from("RUNTIME_SNAPSHOT.bin");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR set by cargo"));
+
+    std::fs::copy(&src, out_dir.join("RUNTIME_SNAPSHOT.bin")).unwrap_or_else(|e| {
+        panic!(
+            "failed to stage {} into OUT_DIR for the `snapshot` feature - generate it first with \
+             `cargo run --bin snapshot -- {}`: {}",
+            src.display(),
+            src.display(),
+            e
+        )
+    });
+}
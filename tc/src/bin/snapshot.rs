@@ -0,0 +1,17 @@
+//! Writes the task-runtime startup snapshot to disk. Run it by hand ahead of a
+//! `--features snapshot` build, so `build.rs` has a file to stage
+//! (see `runtime::snapshot::create_snapshot`).
+
+use std::env;
+use std::path::PathBuf;
+
+use tc::runtime::create_snapshot;
+
+fn main() {
+    let out_path = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("RUNTIME_SNAPSHOT.bin"));
+
+    create_snapshot(&out_path);
+}
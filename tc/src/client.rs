@@ -0,0 +1,104 @@
+use serde::de::DeserializeOwned;
+
+use crate::error::Error;
+use crate::proto::{ClientProto, HostInfo, Message};
+
+/// A connection to the trackway host, from which individual task [`Session`]s are opened.
+pub struct Client {
+    // connection plumbing lives here in the real client; out of scope for this crate slice
+}
+
+impl Client {
+    #[tracing::instrument(skip(self))]
+    pub async fn new_session(&mut self) -> Result<Session, Error> {
+        todo!("open a new session with the host")
+    }
+
+    /// Dials a peer node directly, as opposed to the host connection handed to
+    /// `Runtime::new_with_client`. Used by `cluster::NodeClient` to reach the node that
+    /// owns a remote instance.
+    #[tracing::instrument]
+    pub async fn connect(address: &str) -> Result<Self, Error> {
+        todo!("open a connection to {address}")
+    }
+
+    /// Accepts the next inbound [`Session`] dialed by a peer's [`Client::connect`] - the
+    /// receiving-side counterpart of `new_session`/`connect`, driven by this node's own
+    /// listen loop rather than something this crate slice opens itself. Used by
+    /// `runtime::task_accept_instance` to pick up a peer's `ClaimInstance` for a task this
+    /// node owns.
+    #[tracing::instrument(skip(self))]
+    pub async fn accept(&mut self) -> Result<Session, Error> {
+        todo!("accept the next inbound session from a peer node")
+    }
+}
+
+/// One task's framed message channel with the host.
+pub struct Session {
+    /// The peer's advertised capabilities, populated once [`Session::do_handshake`] completes.
+    peer_info: Option<HostInfo>,
+}
+
+impl Session {
+    /// Exchanges [`HostInfo`] with the peer and records what it supports, so later sends
+    /// through this session can be rejected locally instead of round-tripping to a peer
+    /// that never understood them.
+    #[tracing::instrument(skip(self))]
+    pub async fn do_handshake(&mut self) -> Result<(), Error> {
+        self.send_typed(&ClientProto::Handshake(HostInfo::local())).await?;
+        let peer_info = match self.recv_typed().await? {
+            ClientProto::Handshake(peer_info) => peer_info,
+            _ => return Err(Error::NotFound("expected a Handshake message".to_string())),
+        };
+        self.peer_info = Some(peer_info);
+        Ok(())
+    }
+
+    /// The peer's capabilities, as negotiated by [`Session::do_handshake`].
+    pub fn peer_info(&self) -> Option<&HostInfo> {
+        self.peer_info.as_ref()
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn recv(&self) -> Result<Message, Error> {
+        todo!("read the next framed message off the wire")
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn send(&self, message: Message) -> Result<(), Error> {
+        todo!("write a framed message to the wire")
+    }
+
+    /// Serializes `value` and sends it as a single framed message, tagged with the
+    /// `(MessageType, MessageCode)` pair `value` itself reports via
+    /// [`ClientProto::message_type`]/[`ClientProto::code`] - `ClientProto` is the single
+    /// on-wire contract, so the framing is never chosen separately from the payload.
+    ///
+    /// Rejected locally with `Error::NotFound` if the peer's [`HostInfo`] (once negotiated
+    /// by [`Session::do_handshake`]) doesn't declare support for `value`'s variant, instead
+    /// of round-tripping something we already know it can't understand. Before the
+    /// handshake completes - including the `Handshake` message itself - there's no
+    /// `peer_info` yet, so nothing is gated.
+    #[tracing::instrument(skip(self, value))]
+    pub async fn send_typed(&self, value: &ClientProto) -> Result<(), Error> {
+        if let Some(peer_info) = self.peer_info() {
+            if !peer_info.supports(value.message_type(), value.code()) {
+                return Err(Error::NotFound("peer does not support this message variant".to_string()));
+            }
+        }
+
+        self.send(Message {
+            message_type: value.message_type(),
+            code: value.code(),
+            data: serde_json::to_vec(value)?,
+        })
+        .await
+    }
+
+    /// Receives the next framed message and deserializes its payload as `T`.
+    #[tracing::instrument(skip(self))]
+    pub async fn recv_typed<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let message = self.recv().await?;
+        Ok(serde_json::from_slice(&message.data)?)
+    }
+}
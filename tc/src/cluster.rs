@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::client::{Client, Session};
+use crate::config::Config;
+use crate::error::Error;
+use crate::proto::{ClaimInstanceMessage, ClientProto};
+
+pub type NodeAddress = String;
+
+/// Read-only routing table mapping a task's identity to the node that owns its instance.
+///
+/// Built once from [`Config`] at startup. [`ClusterMetadata::node_for`] returns `None` when
+/// the task should be instantiated locally, either because it isn't in the table or because
+/// it happens to be assigned to this node's own address.
+pub struct ClusterMetadata {
+    self_address: Option<NodeAddress>,
+    assignments: HashMap<String, NodeAddress>,
+}
+
+impl ClusterMetadata {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            self_address: config.node_address.clone(),
+            assignments: config.peers.clone(),
+        }
+    }
+
+    /// The node address owning `task_id`, or `None` if it should be instantiated locally.
+    pub fn node_for(&self, task_id: &str) -> Option<&NodeAddress> {
+        self.assignments.get(task_id)
+            .filter(|address| Some(*address) != self.self_address.as_ref())
+    }
+}
+
+/// Holds the connections this node has opened to peer nodes, keyed by their address.
+///
+/// Mirrors `ClientResource`'s relationship to a single host `Client`, but fanned out over
+/// however many peers this node has proxied instances onto.
+pub struct NodeClient {
+    clients: HashMap<NodeAddress, Client>,
+}
+
+impl NodeClient {
+    pub fn new() -> Self {
+        Self { clients: HashMap::new() }
+    }
+
+    /// Opens (or reuses) a connection to `address` and starts a session there on behalf of
+    /// `task_id`, handshaking capabilities just like a local `task_register_instance` would.
+    #[tracing::instrument(skip(self))]
+    pub async fn open_remote_instance(&mut self, address: &NodeAddress, task_id: &str) -> Result<Session, Error> {
+        if !self.clients.contains_key(address) {
+            self.clients.insert(address.clone(), Client::connect(address).await?);
+        }
+
+        let client = self.clients.get_mut(address).expect("just inserted");
+        let mut session = client.new_session().await?;
+        session.do_handshake().await?;
+        session.send_typed(&ClientProto::ClaimInstance(ClaimInstanceMessage {
+            task_id: task_id.to_string(),
+        })).await?;
+
+        Ok(session)
+    }
+}
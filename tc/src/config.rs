@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use serde::{Serialize, Deserialize};
 
@@ -8,7 +9,17 @@ use crate::error::Error;
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
-    pub token: Option<String>
+    pub token: Option<String>,
+
+    /// This node's own address, as peers should dial it. `None` means this node only ever
+    /// talks to the host and never owns remote instances for other nodes.
+    #[serde(default)]
+    pub node_address: Option<String>,
+
+    /// Static routing table handed to `ClusterMetadata`: task id to the node address that
+    /// owns its instance. Tasks absent from this map run locally.
+    #[serde(default)]
+    pub peers: HashMap<String, String>
 }
 
 impl Config {
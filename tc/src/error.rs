@@ -0,0 +1,104 @@
+use std::fmt;
+use std::io;
+
+use deno_core::error::AnyError;
+
+/// The error type threaded through every fallible op and protocol boundary.
+///
+/// Every variant is mapped to a stable, JS-facing class name by [`Error::class_name`]
+/// so that a caught exception in task script can be told apart by `err.name`
+/// without leaking our internal error representation.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    /// A V8 compilation or execution failure, carrying a description of it. For a script
+    /// that fails to compile or throws, this is a fixed description; V8's own exception
+    /// text is not captured.
+    Js(String),
+    NotFound(String),
+    Interrupted,
+    Other(AnyError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::TomlDe(e) => write!(f, "toml decode error: {}", e),
+            Error::TomlSer(e) => write!(f, "toml encode error: {}", e),
+            Error::Js(msg) => write!(f, "{}", msg),
+            Error::NotFound(what) => write!(f, "not found: {}", what),
+            Error::Interrupted => write!(f, "interrupted"),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::TomlDe(e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::TomlSer(e)
+    }
+}
+
+impl From<serde_v8::Error> for Error {
+    fn from(e: serde_v8::Error) -> Self {
+        Error::Js(e.to_string())
+    }
+}
+
+impl From<AnyError> for Error {
+    fn from(e: AnyError) -> Self {
+        Error::Other(e)
+    }
+}
+
+impl Error {
+    /// The stable class name surfaced to JS as `err.name` via `get_error_class_fn`.
+    ///
+    /// These names are part of our op error contract: task scripts are allowed to
+    /// match on them, so they must not change once shipped.
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            Error::Js(_) => "TypeError",
+            Error::NotFound(_) => "NotFound",
+            Error::Interrupted => "Interrupted",
+            Error::Io(_) | Error::Json(_) | Error::TomlDe(_) | Error::TomlSer(_) | Error::Other(_) => {
+                "Error"
+            }
+        }
+    }
+}
+
+/// Registered as `OpState::get_error_class_fn` so every op error - boxed as an
+/// `AnyError` by the `#[op]` macro - is mapped back to a stable class name instead
+/// of deno_core's generic `"Error"` fallback.
+pub fn get_error_class(e: &AnyError) -> &'static str {
+    match e.downcast_ref::<Error>() {
+        Some(e) => e.class_name(),
+        None => "Error",
+    }
+}
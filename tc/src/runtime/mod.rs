@@ -1,52 +1,33 @@
-use std::borrow::Cow;
 use std::cell::{RefCell, RefMut};
 use std::error::Error as StdError;
 use std::path::PathBuf;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
-use std::sync::Arc;
 use deno_ast::ModuleSpecifier;
-use serde::{Serialize, Deserialize};
 
 use deno_core::{op, v8, Op, JsRuntime, ModuleId, ResourceId, OpState, AsyncRefCell, Resource, AsyncMutFuture, AsyncRefFuture, RcRef, Extension};
 use deno_core::error::AnyError;
 use deno_core::v8::HandleScope;
 use futures::{SinkExt, TryFutureExt};
-use swc::TransformOutput;
 use crate::client::{Client, Session};
 
 mod compile;
 mod emit;
+mod snapshot;
 use emit::Emitter;
+pub use snapshot::create_snapshot;
 
+use crate::cluster::{ClusterMetadata, NodeAddress, NodeClient};
 use crate::error::Error;
-use crate::proto::{MessageBuilder, MessageCode};
-use crate::proto::trackway::MessageType;
+use crate::proto::ClientProto;
 use crate::runtime::compile::{Compiler, TaskMapResource};
 
 const CLIENT_RID: ResourceId = 0;
 const TASK_MAP_RID: ResourceId = 1;
-
-#[derive(Serialize)]
-pub struct NewInstanceMessage {
-    task_name: String,
-    task_description: String,
-    task_context: Arc<TransformOutput>,
-    instance_id: ResourceId
-}
-
-#[derive(Deserialize)]
-pub struct EvaluateScriptMessage {
-    instance_id: ResourceId,
-    source_code: String
-}
-
-#[derive(Serialize)]
-pub struct JsonValueMessage {
-    instance_id: ResourceId,
-    value: serde_json::Value
-}
+const CLUSTER_METADATA_RID: ResourceId = 2;
+const NODE_CLIENT_RID: ResourceId = 3;
+const INSTANCE_REGISTRY_RID: ResourceId = 4;
 
 pub struct RuntimeOptions {
     client: Client,
@@ -60,30 +41,51 @@ pub struct Runtime {
 
 impl Runtime {
     #[tracing::instrument(skip(client))]
-    pub fn new_with_client(client: Client) -> Self {
+    pub fn new_with_client(client: Client, cluster_metadata: ClusterMetadata) -> Self {
         let compiler = Compiler::new();
 
+        #[cfg(feature = "snapshot")]
+        let startup_snapshot = Some(deno_core::Snapshot::Static(snapshot::STARTUP_SNAPSHOT));
+        #[cfg(not(feature = "snapshot"))]
+        let startup_snapshot = None;
+
         let rt = JsRuntime::new(deno_core::RuntimeOptions {
             module_loader: Some(Rc::new(compiler.into_module_loader())),
+            startup_snapshot,
             extensions: vec![
                 Extension {
-                    ops: Cow::from(vec![
-                        task_register_instance::DECL,
-                        task_poll_instance::DECL,
-                        task_run_with_side_effects::DECL,
-                        task_cancel_instance::DECL
-                    ]),
-                    op_state_fn: Some(Box::new(|op_state| {
+                    op_state_fn: Some(Box::new(move |op_state| {
                         let client_resource = ClientResource::from_client(client);
                         assert_eq!(op_state.resource_table.add(client_resource), CLIENT_RID);
                         assert_eq!(op_state.resource_table.add_rc(task_map_resource), TASK_MAP_RID);
+                        assert_eq!(
+                            op_state.resource_table.add(ClusterMetadataResource::from_metadata(cluster_metadata)),
+                            CLUSTER_METADATA_RID
+                        );
+                        assert_eq!(
+                            op_state.resource_table.add(NodeClientResource::new()),
+                            NODE_CLIENT_RID
+                        );
+                        assert_eq!(
+                            op_state.resource_table.add(InstanceRegistry::new()),
+                            INSTANCE_REGISTRY_RID
+                        );
+                        op_state.get_error_class_fn = &crate::error::get_error_class;
                     })),
-                    ..Default::default()
+                    ..snapshot::ops_extension()
                 }
             ],
             ..Default::default()
         });
 
+        // In a snapshotting build, `bootstrap.js` is already materialized inside
+        // `startup_snapshot`; only evaluate it here on the non-snapshot development path.
+        #[cfg(not(feature = "snapshot"))]
+        let mut rt = rt;
+        #[cfg(not(feature = "snapshot"))]
+        rt.execute_script("bootstrap.js", snapshot::BOOTSTRAP_JS)
+            .expect("bootstrap.js failed to evaluate");
+
         Self {
             rt
         }
@@ -122,11 +124,48 @@ impl ClientResource {
     }
 }
 
+/// Wraps the read-only task-to-node routing table so it can live in the resource table
+/// alongside the other cluster resources.
+pub struct ClusterMetadataResource(ClusterMetadata);
+
+impl ClusterMetadataResource {
+    pub fn from_metadata(metadata: ClusterMetadata) -> Self {
+        Self(metadata)
+    }
+
+    pub fn node_for(&self, task_id: &str) -> Option<NodeAddress> {
+        self.0.node_for(task_id).cloned()
+    }
+}
+
+impl Resource for ClusterMetadataResource {}
+
+pub struct NodeClientResource {
+    inner: AsyncRefCell<NodeClient>
+}
+
+impl NodeClientResource {
+    pub fn new() -> Self {
+        Self {
+            inner: AsyncRefCell::new(NodeClient::new())
+        }
+    }
+}
+
+impl Resource for NodeClientResource {}
+
+impl NodeClientResource {
+    pub fn borrow_mut(self: Rc<Self>) -> AsyncMutFuture<NodeClient> {
+        RcRef::map(self, |this| &this.inner).borrow_mut()
+    }
+}
+
 pub type SlotId = ResourceId;
 
 pub enum Slot {
     Source(String),
-    Ok(serde_json::Value)
+    Ok(serde_json::Value),
+    Err(Error)
 }
 
 pub struct Instance {
@@ -152,17 +191,17 @@ impl Instance {
     pub async fn poll(&mut self, slot_id: Option<SlotId>) -> Result<SlotId, Error> {
         if let Some(slot_id) = slot_id {
             match self.slots.remove(&slot_id) {
-                Some(Slot::Ok(value)) => MessageBuilder::new()
-                    .message_type(MessageType::MessagePipe)
-                    .code(MessageCode::Ok)
-                    .data(serde_json::to_vec(&value).unwrap())
-                    .send(&self.session)
-                    .await?,
+                Some(Slot::Ok(value)) => self.session.send_typed(&ClientProto::Ok(value)).await?,
+                Some(Slot::Err(err)) => self.session.send_typed(&ClientProto::Err(err.to_string())).await?,
                 _ => {}
             };
         }
-        let message = self.session.recv().await?;
-        let EvaluateScriptMessage { source_code, .. } = serde_json::from_slice(&message.data)?;
+        let proto: ClientProto = self.session.recv_typed().await?;
+
+        let source_code = match proto {
+            ClientProto::EvaluateScript(message) => message.source_code,
+            _ => return Err(Error::NotFound("expected an EvaluateScript message".to_string())),
+        };
 
         let slot_id = self.next_slot;
         self.slots.insert(slot_id, Slot::Source(source_code));
@@ -172,21 +211,38 @@ impl Instance {
     }
 
     pub fn run<'s>(&mut self, scope: &mut HandleScope<'s>, slot_id: SlotId) -> Result<(), Error> {
-        let slot = match self.slots.remove(&slot_id).unwrap() {
-            Slot::Source(source_code) => {
-                let source_value = v8::String::new(scope, &source_code).unwrap();
-                let script = v8::Script::compile(scope, source_value, None).unwrap();
-                let result_value = script.run(scope).unwrap();
-                let as_json: serde_json::Value = serde_v8::from_v8(scope, result_value).unwrap();
-                Slot::Ok(as_json)
-            },
+        let slot = self.slots.remove(&slot_id)
+            .ok_or_else(|| Error::NotFound(format!("slot {slot_id}")))?;
+
+        let slot = match slot {
+            Slot::Source(source_code) => Self::compile_and_run(scope, &source_code)
+                .unwrap_or_else(Slot::Err),
             otherwise => otherwise
         };
 
-        self.slots.insert(slot_id, slot).unwrap();
+        self.slots.insert(slot_id, slot);
 
         Ok(())
     }
+
+    /// Compiles and runs `source_code` as a classic (non-module) V8 script, returning the
+    /// compile/exec failure as an [`Error`] rather than panicking. `run` stores it as a
+    /// `Slot::Err`, and the next `poll` sends it back over the session as a
+    /// `ClientProto::Err` instead of killing the runtime.
+    fn compile_and_run<'s>(scope: &mut HandleScope<'s>, source_code: &str) -> Result<Slot, Error> {
+        let source_value = v8::String::new(scope, source_code)
+            .ok_or_else(|| Error::Js("failed to allocate source string".to_string()))?;
+
+        let script = v8::Script::compile(scope, source_value, None)
+            .ok_or_else(|| Error::Js("failed to compile script".to_string()))?;
+
+        let result_value = script
+            .run(scope)
+            .ok_or_else(|| Error::Js("uncaught exception while running script".to_string()))?;
+
+        let as_json: serde_json::Value = serde_v8::from_v8(scope, result_value)?;
+        Ok(Slot::Ok(as_json))
+    }
 }
 
 pub struct InstanceResource(RefCell<Instance>);
@@ -194,36 +250,176 @@ pub struct InstanceResource(RefCell<Instance>);
 impl Resource for InstanceResource {}
 
 impl InstanceResource {
-    pub fn from_op_state(state: Rc<RefCell<OpState>>, instance_id: ResourceId) -> Rc<Self> {
-        state.borrow_mut().resource_table.get::<Self>(instance_id).unwrap()
-    }
-
     pub fn borrow_mut(&self) -> RefMut<'_, Instance> {
         self.0.borrow_mut()
     }
 }
 
+/// A proxy standing in for an instance owned by another node.
+///
+/// `host_session` talks to the same host a local [`Instance`] would, while `node_session`
+/// talks to the owning node (opened by `cluster::NodeClient::open_remote_instance`). `poll`
+/// relays one `EvaluateScript`/`Ok`/`Err` round trip between the two, so from the owning
+/// node's side this session is indistinguishable from a host talking to its own `Instance`.
+pub struct RemoteInstance {
+    host_session: Session,
+    node_session: Session,
+    next_slot: SlotId
+}
+
+impl RemoteInstance {
+    pub fn new(host_session: Session, node_session: Session) -> Self {
+        Self { host_session, node_session, next_slot: 0 }
+    }
+
+    pub async fn poll(&mut self, _slot_id: Option<SlotId>) -> Result<SlotId, Error> {
+        let proto: ClientProto = self.host_session.recv_typed().await?;
+
+        let message = match proto {
+            ClientProto::EvaluateScript(message) => message,
+            _ => return Err(Error::NotFound("expected an EvaluateScript message".to_string())),
+        };
+
+        self.node_session.send_typed(&ClientProto::EvaluateScript(message)).await?;
+
+        let reply: ClientProto = self.node_session.recv_typed().await?;
+        match &reply {
+            ClientProto::Ok(_) | ClientProto::Err(_) => self.host_session.send_typed(&reply).await?,
+            _ => return Err(Error::NotFound("expected an Ok/Err reply from the owning node".to_string())),
+        }
+
+        let slot_id = self.next_slot;
+        self.next_slot += 1;
+        Ok(slot_id)
+    }
+
+    /// The owning node performs the actual side-effecting run as part of its own `poll`
+    /// loop, so there is nothing left to execute locally - this only exists so call sites
+    /// can treat local and remote instances identically.
+    pub fn run(&mut self, _slot_id: SlotId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    pub async fn cancel(&mut self) -> Result<(), Error> {
+        self.node_session.send_typed(&ClientProto::Cancel).await
+    }
+}
+
+/// Whether an instance behind a [`ResourceId`] is local to this node or proxied to a peer.
+#[derive(Clone)]
+pub enum Placement {
+    Local(Rc<InstanceResource>),
+    Remote(Rc<RefCell<RemoteInstance>>)
+}
+
+/// Tracks where every task instance actually lives, replacing direct `resource_table`
+/// lookups so `Instance`s and `RemoteInstance`s can be looked up through one `ResourceId`
+/// space regardless of placement.
+pub struct InstanceRegistry {
+    next_id: RefCell<ResourceId>,
+    placements: RefCell<HashMap<ResourceId, Placement>>
+}
+
+impl Resource for InstanceRegistry {}
+
+impl InstanceRegistry {
+    pub fn new() -> Self {
+        Self {
+            next_id: RefCell::new(0),
+            placements: RefCell::new(HashMap::new())
+        }
+    }
+
+    fn insert(&self, placement: Placement) -> ResourceId {
+        let mut next_id = self.next_id.borrow_mut();
+        let id = *next_id;
+        *next_id += 1;
+        self.placements.borrow_mut().insert(id, placement);
+        id
+    }
+
+    pub fn insert_local(&self, instance: Instance) -> ResourceId {
+        self.insert(Placement::Local(Rc::new(instance.into_resource())))
+    }
+
+    pub fn insert_remote(&self, remote: RemoteInstance) -> ResourceId {
+        self.insert(Placement::Remote(Rc::new(RefCell::new(remote))))
+    }
+
+    pub fn get(&self, instance_id: ResourceId) -> Option<Placement> {
+        self.placements.borrow().get(&instance_id).cloned()
+    }
+
+    pub fn remove(&self, instance_id: ResourceId) -> Option<Placement> {
+        self.placements.borrow_mut().remove(&instance_id)
+    }
+}
+
 #[op]
 #[tracing::instrument(skip(state))]
 async fn task_register_instance(
     state: Rc<RefCell<OpState>>,
     task_id: String
-) -> ResourceId {
-    let (task_map, op_client) = {
+) -> Result<ResourceId, AnyError> {
+    let (task_map, op_client, cluster_metadata, node_client, registry) = {
         let resource_table = &mut state.borrow_mut().resource_table;
         (
             resource_table.get::<TaskMapResource>(TASK_MAP_RID).unwrap(),
-            resource_table.get::<ClientResource>(CLIENT_RID).unwrap()
+            resource_table.get::<ClientResource>(CLIENT_RID).unwrap(),
+            resource_table.get::<ClusterMetadataResource>(CLUSTER_METADATA_RID).unwrap(),
+            resource_table.get::<NodeClientResource>(NODE_CLIENT_RID).unwrap(),
+            resource_table.get::<InstanceRegistry>(INSTANCE_REGISTRY_RID).unwrap()
         )
     };
 
-    let mut session = op_client.borrow_mut().await.new_session().await?;
+    let instance_id = if let Some(address) = cluster_metadata.node_for(&task_id) {
+        let node_session = node_client.borrow_mut().await.open_remote_instance(&address, &task_id).await?;
+
+        let mut host_session = op_client.borrow_mut().await.new_session().await?;
+        host_session.do_handshake().await?;
+
+        registry.insert_remote(RemoteInstance::new(host_session, node_session))
+    } else {
+        let mut session = op_client.borrow_mut().await.new_session().await?;
+        session.do_handshake().await?;
+        registry.insert_local(Instance::from_session(session))
+    };
+
+    Ok(instance_id)
+}
+
+/// The receiving-side counterpart of `task_register_instance`'s remote branch: called on the
+/// node a task is actually owned by, once its listen loop (out of scope of this crate slice,
+/// same as `Client::accept` itself) has a fresh inbound session from
+/// `cluster::NodeClient::open_remote_instance`. Reads the `ClaimInstance` the peer sends
+/// right after the handshake and attaches a local [`Instance`] to that session, so the rest
+/// of its `EvaluateScript`/`Ok`/`Err` traffic is served exactly like any other registered
+/// instance - the caller uses the returned `task_id` to correlate the new `ResourceId` with
+/// whatever bookkeeping it does for that task.
+#[op]
+#[tracing::instrument(skip(state))]
+async fn task_accept_instance(
+    state: Rc<RefCell<OpState>>,
+) -> Result<(String, ResourceId), AnyError> {
+    let (op_client, registry) = {
+        let resource_table = &mut state.borrow_mut().resource_table;
+        (
+            resource_table.get::<ClientResource>(CLIENT_RID).unwrap(),
+            resource_table.get::<InstanceRegistry>(INSTANCE_REGISTRY_RID).unwrap()
+        )
+    };
 
+    let mut session = op_client.borrow_mut().await.accept().await?;
     session.do_handshake().await?;
 
-    let instance = Instance::from_session(session);
+    let task_id = match session.recv_typed().await? {
+        ClientProto::ClaimInstance(message) => message.task_id,
+        _ => return Err(Error::NotFound("expected a ClaimInstance message".to_string()).into()),
+    };
+
+    let instance_id = registry.insert_local(Instance::from_session(session));
 
-    state.borrow_mut().resource_table.add(instance.into_resource())
+    Ok((task_id, instance_id))
 }
 
 #[op]
@@ -233,11 +429,11 @@ async fn task_poll_instance(
     instance_id: ResourceId,
     slot_id: Option<ResourceId>
 ) -> Result<ResourceId, AnyError> {
-    InstanceResource::from_op_state(state, instance_id)
-        .borrow_mut()
-        .poll(slot_id)
-        .await
-        .map_err(|_| todo!())
+    let placement = registry_lookup(&state, instance_id)?;
+    match placement {
+        Placement::Local(instance) => instance.borrow_mut().poll(slot_id).await.map_err(Into::into),
+        Placement::Remote(remote) => remote.borrow_mut().poll(slot_id).await.map_err(Into::into)
+    }
 }
 
 #[op]
@@ -249,9 +445,10 @@ fn task_run_with_side_effects<'s>(
     slot_id: Option<ResourceId>,
 ) -> Result<(), AnyError> {
     if let Some(slot_id) = slot_id {
-        InstanceResource::from_op_state(state, instance_id)
-            .borrow_mut()
-            .run(scope, slot_id)?;
+        match registry_lookup(&state, instance_id)? {
+            Placement::Local(instance) => instance.borrow_mut().run(scope, slot_id)?,
+            Placement::Remote(remote) => remote.borrow_mut().run(slot_id)?
+        }
     }
     Ok(())
 }
@@ -262,5 +459,18 @@ async fn task_cancel_instance(
     state: Rc<RefCell<OpState>>,
     instance_id: ResourceId
 ) -> Result<(), AnyError> {
-    state.borrow_mut().resource_table.close(instance_id)
-}
\ No newline at end of file
+    let registry = state.borrow_mut().resource_table.get::<InstanceRegistry>(INSTANCE_REGISTRY_RID).unwrap();
+    if let Some(Placement::Remote(remote)) = registry.remove(instance_id) {
+        remote.borrow_mut().cancel().await?;
+    }
+    Ok(())
+}
+
+/// Looks up `instance_id` in the [`InstanceRegistry`], surfacing a catchable `NotFound`
+/// instead of the panic a raw `resource_table.get` would give on an unknown id.
+fn registry_lookup(state: &Rc<RefCell<OpState>>, instance_id: ResourceId) -> Result<Placement, AnyError> {
+    let registry = state.borrow_mut().resource_table.get::<InstanceRegistry>(INSTANCE_REGISTRY_RID).unwrap();
+    registry
+        .get(instance_id)
+        .ok_or_else(|| Error::NotFound(format!("instance {instance_id}")).into())
+}
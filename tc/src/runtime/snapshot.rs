@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use deno_core::{Extension, JsRuntime, Op, Snapshot};
+
+use crate::runtime::{
+    task_accept_instance, task_cancel_instance, task_poll_instance, task_register_instance,
+    task_run_with_side_effects,
+};
+
+/// The task-runtime bootstrap JS evaluated into every `JsRuntime` before any user script
+/// runs. Baked into the startup snapshot by `create_snapshot` so a snapshotting build never
+/// has to re-parse or re-evaluate it on process start.
+pub(crate) const BOOTSTRAP_JS: &str = include_str!("../../js/bootstrap.js");
+
+/// The startup snapshot written by `create_snapshot` ahead of the build and staged into
+/// `OUT_DIR` by `build.rs`, when the `snapshot` feature is enabled.
+/// `Runtime::new_with_client` passes this straight to
+/// `RuntimeOptions::startup_snapshot` instead of evaluating `BOOTSTRAP_JS` at boot.
+#[cfg(feature = "snapshot")]
+pub(crate) static STARTUP_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/RUNTIME_SNAPSHOT.bin"));
+
+/// The ops extension shared by `create_snapshot`'s build-time runtime and
+/// `Runtime::new_with_client`'s live one, so the two can never drift out of sync with each
+/// other - a mismatch would desync the op layout baked into `STARTUP_SNAPSHOT` from the one
+/// `new_with_client` expects at load time.
+pub(crate) fn ops_extension() -> Extension {
+    Extension {
+        ops: Cow::from(vec![
+            task_register_instance::DECL,
+            task_accept_instance::DECL,
+            task_poll_instance::DECL,
+            task_run_with_side_effects::DECL,
+            task_cancel_instance::DECL
+        ]),
+        ..Default::default()
+    }
+}
+
+/// Builds a fresh `JsRuntime` wired with our ops, evaluates the bootstrap JS, and writes a
+/// V8 startup snapshot of the result to `out_path`.
+///
+/// Called by the `snapshot` binary, whose output `build.rs` stages for the `snapshot`
+/// feature; `build.rs` does not call this itself. Client/cluster state
+/// isn't available at build time, so the snapshotted runtime only carries ops, not the
+/// resources `Runtime::new_with_client` installs via `op_state_fn` at process start.
+pub fn create_snapshot(out_path: &Path) {
+    let mut rt = JsRuntime::new(deno_core::RuntimeOptions {
+        extensions: vec![ops_extension()],
+        will_snapshot: true,
+        ..Default::default()
+    });
+
+    rt.execute_script("bootstrap.js", BOOTSTRAP_JS)
+        .expect("bootstrap.js failed to evaluate while building the startup snapshot");
+
+    let snapshot = rt.snapshot();
+    std::fs::write(out_path, &*snapshot)
+        .unwrap_or_else(|e| panic!("failed to write snapshot to {}: {}", out_path.display(), e));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_runtime(startup_snapshot: Option<Snapshot>) -> JsRuntime {
+        JsRuntime::new(deno_core::RuntimeOptions {
+            extensions: vec![ops_extension()],
+            startup_snapshot,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn ops_resolve_with_and_without_a_snapshot() {
+        for startup_snapshot in [None, Some(build_test_snapshot())] {
+            let mut rt = new_runtime(startup_snapshot);
+            let value = rt
+                .execute_script("check_ops.js", "typeof Deno.core.ops.task_register_instance")
+                .unwrap();
+            let scope = &mut rt.handle_scope();
+            assert_eq!(value.open(scope).to_rust_string_lossy(scope), "function");
+        }
+    }
+
+    #[test]
+    fn a_trivial_script_evaluates_identically_with_and_without_a_snapshot() {
+        let without_snapshot = eval_trivial_script(new_runtime(None), true);
+        let with_snapshot = eval_trivial_script(new_runtime(Some(build_test_snapshot())), false);
+        assert_eq!(without_snapshot, with_snapshot);
+    }
+
+    /// Evaluates a trivial script in `rt`, running `bootstrap.js` first only if
+    /// `run_bootstrap` is set. A snapshot-restored runtime already has `bootstrap.js`
+    /// materialized; re-running it there would evaluate over already-bootstrapped state
+    /// instead of proving the restore itself preserved it. Either way, asserts
+    /// `globalThis.Trackway` is visible before the trivial script runs, without executing
+    /// `BOOTSTRAP_JS` again to get there.
+    fn eval_trivial_script(mut rt: JsRuntime, run_bootstrap: bool) -> String {
+        if run_bootstrap {
+            rt.execute_script("bootstrap.js", BOOTSTRAP_JS).unwrap();
+        }
+
+        let has_trackway = rt
+            .execute_script("check_bootstrap.js", "String(typeof globalThis.Trackway !== 'undefined')")
+            .unwrap();
+        {
+            let scope = &mut rt.handle_scope();
+            assert_eq!(has_trackway.open(scope).to_rust_string_lossy(scope), "true");
+        }
+
+        let value = rt.execute_script("trivial.js", "1 + 1").unwrap();
+        let scope = &mut rt.handle_scope();
+        value.open(scope).to_rust_string_lossy(scope)
+    }
+
+    fn build_test_snapshot() -> Snapshot {
+        let mut rt = JsRuntime::new(deno_core::RuntimeOptions {
+            extensions: vec![ops_extension()],
+            will_snapshot: true,
+            ..Default::default()
+        });
+        rt.execute_script("bootstrap.js", BOOTSTRAP_JS).unwrap();
+        Snapshot::JustCreated(rt.snapshot())
+    }
+}